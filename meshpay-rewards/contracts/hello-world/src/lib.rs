@@ -1,23 +1,71 @@
 #![no_std]
-use soroban_sdk::{contract, contractimpl, contracttype, token, Address, Env, String};
+use soroban_sdk::{contract, contractimpl, contracttype, token, Address, Env, String, Vec};
 
-// Fee structure: 1% total
+// Default fee structure seeded at initialize: 1% total
 // - 0.5% to broadcaster (first relay peer)
 // - 0.1% to relayer (submitter to blockchain)
 // - 0.4% to protocol (contract deployer)
-const TOTAL_FEE_BPS: u64 = 100;        // 1% in basis points (10000 = 100%)
-const BROADCASTER_FEE_BPS: u64 = 50;   // 0.5%
-const RELAYER_FEE_BPS: u64 = 10;       // 0.1%
-const PROTOCOL_FEE_BPS: u64 = 40;      // 0.4%
+// The live schedule is governed after that — see `FeeSchedule`/`update_fees`.
+const DEFAULT_BROADCASTER_FEE_BPS: u32 = 50; // 0.5%
+const DEFAULT_RELAYER_FEE_BPS: u32 = 10; // 0.1%
+const DEFAULT_PROTOCOL_FEE_BPS: u32 = 40; // 0.4%
+const MAX_TOTAL_FEE_BPS: u32 = 10000; // 100% — sanity ceiling for update_fees
+
+// Largest payment amount that can't overflow `amount * bps` in i128 math,
+// since bps is bounded by MAX_TOTAL_FEE_BPS (10000).
+const MAX_SAFE_AMOUNT: i128 = i128::MAX / MAX_TOTAL_FEE_BPS as i128;
+
+// Congestion-based quoting: the effective bps scales up with recent demand
+// and resets once the activity window rolls over.
+const METRICS_WINDOW_LEDGERS: u32 = 100; // ledgers before the activity window resets
+const METRICS_CAPACITY: u32 = 50; // payments_in_window at which utilization doubles the base fee
+const MAX_QUOTE_BPS: u32 = 500; // hard ceiling on the effective total bps (5%)
+
+#[contracttype]
+#[derive(Clone)]
+pub struct FeeSchedule {
+    pub broadcaster_bps: u32,
+    pub relayer_bps: u32,
+    pub protocol_bps: u32,
+}
+
+impl FeeSchedule {
+    fn total_bps(&self) -> u32 {
+        self.broadcaster_bps + self.relayer_bps + self.protocol_bps
+    }
+}
 
 #[contracttype]
 #[derive(Clone)]
 pub struct Payment {
     pub sender: Address,
     pub recipient: Address,
-    pub broadcaster: Address,
+    /// Ordered relay hops the packet traversed before reaching the relayer.
+    /// The broadcaster fee is split across these peers.
+    pub relay_hops: Vec<Address>,
+    /// Per-hop weights parallel to `relay_hops`. Empty means split evenly.
+    pub hop_weights: Vec<u32>,
     pub relayer: Address,
+    /// Net amount owed to the recipient once fees are settled.
     pub amount: i128,
+    /// When `true`, the fee is taken out of `amount` and the recipient bears it
+    /// (current default behavior). When `false`, the recipient is made whole
+    /// for the full `amount` and the sender is charged `amount + total_fee`.
+    pub fee_included: bool,
+    /// Fee split locked in from the live quote (see `quote_fee`) at creation
+    /// time, so neither `update_fees` nor later congestion moves what an
+    /// escrowed payment owes.
+    pub broadcaster_fee: i128,
+    pub relayer_fee: i128,
+    pub protocol_fee: i128,
+    /// Total pulled from the sender into escrow at creation.
+    pub gross_amount: i128,
+    /// Effective total bps locked in at creation time.
+    pub quoted_bps: u32,
+    /// Token the payment is escrowed and settled in.
+    pub token_address: Address,
+    /// Set and stored by `claim_payment` before it makes any transfer;
+    /// guards against double-claiming the escrow.
     pub claimed: bool,
 }
 
@@ -26,6 +74,138 @@ pub enum DataKey {
     Payment(u64),      // payment_id -> Payment
     PaymentCount,      // total number of payments
     Protocol,          // protocol fee recipient (deployer)
+    FeeSchedule,       // live, governable fee schedule
+    Earnings(Address), // address -> lifetime reward earnings
+    TotalDistributed,  // cumulative fees paid out, by category
+    Metrics,           // recent network activity driving congestion quoting
+}
+
+/// Recent network activity used to scale the live fee quote with demand.
+#[contracttype]
+#[derive(Clone)]
+pub struct QuotingMetrics {
+    pub payments_in_window: u32,
+    pub window_start_ledger: u32,
+}
+
+/// Cumulative fees paid out across all payments, by category.
+#[contracttype]
+#[derive(Clone)]
+pub struct Totals {
+    pub broadcaster: i128,
+    pub relayer: i128,
+    pub protocol: i128,
+}
+
+/// Add `amount` to `address`'s lifetime earnings, panicking on overflow
+/// instead of silently wrapping.
+fn accrue_earnings(env: &Env, address: &Address, amount: i128) {
+    let key = DataKey::Earnings(address.clone());
+    let current: i128 = env.storage().instance().get(&key).unwrap_or(0);
+    let updated = current
+        .checked_add(amount)
+        .expect("Earnings overflow for address");
+    env.storage().instance().set(&key, &updated);
+}
+
+/// Add this payment's fee split to the protocol-wide running totals,
+/// panicking on overflow instead of silently wrapping.
+fn accrue_totals(env: &Env, broadcaster_fee: i128, relayer_fee: i128, protocol_fee: i128) {
+    let mut totals: Totals = env
+        .storage()
+        .instance()
+        .get(&DataKey::TotalDistributed)
+        .unwrap_or(Totals {
+            broadcaster: 0,
+            relayer: 0,
+            protocol: 0,
+        });
+    totals.broadcaster = totals
+        .broadcaster
+        .checked_add(broadcaster_fee)
+        .expect("Total distributed overflow");
+    totals.relayer = totals
+        .relayer
+        .checked_add(relayer_fee)
+        .expect("Total distributed overflow");
+    totals.protocol = totals
+        .protocol
+        .checked_add(protocol_fee)
+        .expect("Total distributed overflow");
+    env.storage()
+        .instance()
+        .set(&DataKey::TotalDistributed, &totals);
+}
+
+/// Roll the activity window used for congestion quoting over to a fresh,
+/// empty window once `METRICS_WINDOW_LEDGERS` have elapsed; otherwise return
+/// it unchanged. Does not count any payment itself. Read-only, so
+/// both the mutating `record_payment_metrics` and the read-only `quote_fee`
+/// see the same rollover without either of them going stale.
+fn rolled_over_metrics(env: &Env, metrics: QuotingMetrics) -> QuotingMetrics {
+    let current_ledger = env.ledger().sequence();
+    if current_ledger - metrics.window_start_ledger >= METRICS_WINDOW_LEDGERS {
+        QuotingMetrics {
+            payments_in_window: 0,
+            window_start_ledger: current_ledger,
+        }
+    } else {
+        metrics
+    }
+}
+
+/// Roll the activity window over if due, then bump it for this payment.
+fn record_payment_metrics(env: &Env) -> QuotingMetrics {
+    let metrics: QuotingMetrics =
+        env.storage()
+            .instance()
+            .get(&DataKey::Metrics)
+            .unwrap_or(QuotingMetrics {
+                payments_in_window: 0,
+                window_start_ledger: env.ledger().sequence(),
+            });
+
+    let mut metrics = rolled_over_metrics(env, metrics);
+    metrics.payments_in_window += 1;
+
+    env.storage().instance().set(&DataKey::Metrics, &metrics);
+    metrics
+}
+
+/// Quote the fee for `amount` given `schedule` (proportions) and `metrics`
+/// (demand): the effective total bps scales up with recent payment volume,
+/// clamped to `MAX_QUOTE_BPS`, then splits in the schedule's proportions
+/// with any rounding dust assigned to the protocol share.
+/// Returns (total_fee, broadcaster_fee, relayer_fee, protocol_fee, effective_bps).
+fn quote_fee_breakdown(
+    amount: i128,
+    schedule: &FeeSchedule,
+    metrics: &QuotingMetrics,
+) -> (i128, i128, i128, i128, u32) {
+    let base_bps = schedule.total_bps();
+    let utilization_bps =
+        (base_bps as u64 * metrics.payments_in_window as u64) / METRICS_CAPACITY as u64;
+    let effective_bps = ((base_bps as u64 + utilization_bps).min(MAX_QUOTE_BPS as u64)) as u32;
+
+    let total_fee = (amount * effective_bps as i128) / 10000;
+    let (broadcaster_fee, relayer_fee) = if base_bps == 0 {
+        (0, 0)
+    } else {
+        (
+            (total_fee * schedule.broadcaster_bps as i128) / base_bps as i128,
+            (total_fee * schedule.relayer_bps as i128) / base_bps as i128,
+        )
+    };
+    // Dust from the proportional split goes to the protocol share.
+    let protocol_fee = total_fee - broadcaster_fee - relayer_fee;
+
+    (
+        total_fee,
+        broadcaster_fee,
+        relayer_fee,
+        protocol_fee,
+        effective_bps,
+    )
 }
 
 #[contract]
@@ -33,28 +213,172 @@ pub struct MeshPayRewards;
 
 #[contractimpl]
 impl MeshPayRewards {
-    /// Initialize the contract with protocol address (deployer)
+    /// Initialize the contract with protocol address (deployer) and seed the
+    /// default fee schedule.
     pub fn initialize(env: Env, protocol: Address) {
         if env.storage().instance().has(&DataKey::Protocol) {
             panic!("Already initialized");
         }
         env.storage().instance().set(&DataKey::Protocol, &protocol);
         env.storage().instance().set(&DataKey::PaymentCount, &0u64);
+        env.storage().instance().set(
+            &DataKey::FeeSchedule,
+            &FeeSchedule {
+                broadcaster_bps: DEFAULT_BROADCASTER_FEE_BPS,
+                relayer_bps: DEFAULT_RELAYER_FEE_BPS,
+                protocol_bps: DEFAULT_PROTOCOL_FEE_BPS,
+            },
+        );
+    }
+
+    /// Update the live fee schedule. Only the protocol address (deployer) may
+    /// call this, and the three shares must not add up to more than 100%.
+    pub fn update_fees(env: Env, broadcaster_bps: u32, relayer_bps: u32, protocol_bps: u32) {
+        let protocol: Address = env
+            .storage()
+            .instance()
+            .get(&DataKey::Protocol)
+            .expect("Protocol address not set");
+        protocol.require_auth();
+
+        let schedule = FeeSchedule {
+            broadcaster_bps,
+            relayer_bps,
+            protocol_bps,
+        };
+        if schedule.total_bps() > MAX_TOTAL_FEE_BPS {
+            panic!("Total fee exceeds 100%");
+        }
+
+        env.storage()
+            .instance()
+            .set(&DataKey::FeeSchedule, &schedule);
+    }
+
+    /// Get the live fee schedule so clients can fetch the current breakdown
+    /// before building a transaction.
+    pub fn get_fee_schedule(env: Env) -> FeeSchedule {
+        env.storage()
+            .instance()
+            .get(&DataKey::FeeSchedule)
+            .expect("Fee schedule not set")
+    }
+
+    /// Quote the live, congestion-based fee for `amount`: the effective
+    /// total bps scales up with recent payment volume (see `QuotingMetrics`),
+    /// clamped to `MAX_QUOTE_BPS`, then splits in the fee schedule's
+    /// broadcaster/relayer/protocol proportions. This is the quote
+    /// `create_payment` locks onto a payment at creation time.
+    pub fn quote_fee(env: Env, amount: i128) -> (i128, i128, i128, i128) {
+        if amount < 0 || amount > MAX_SAFE_AMOUNT {
+            panic!("amount out of safe range");
+        }
+
+        let schedule: FeeSchedule = env
+            .storage()
+            .instance()
+            .get(&DataKey::FeeSchedule)
+            .expect("Fee schedule not set");
+        let metrics: QuotingMetrics =
+            env.storage()
+                .instance()
+                .get(&DataKey::Metrics)
+                .unwrap_or(QuotingMetrics {
+                    payments_in_window: 0,
+                    window_start_ledger: env.ledger().sequence(),
+                });
+        let mut metrics = rolled_over_metrics(&env, metrics);
+        // `create_payment` counts the new payment toward the window before
+        // quoting it, so count the prospective payment here as well.
+        metrics.payments_in_window += 1;
+
+        let (total_fee, broadcaster_fee, relayer_fee, protocol_fee, _effective_bps) =
+            quote_fee_breakdown(amount, &schedule, &metrics);
+
+        (
+            amount - total_fee,
+            broadcaster_fee,
+            relayer_fee,
+            protocol_fee,
+        )
     }
 
-    /// Create a new payment with broadcaster and relayer info
-    /// Returns payment_id
+    /// Create a new payment and escrow its funds. `relay_hops` is the ordered
+    /// list of mesh peers the packet traversed; `hop_weights`, if non-empty,
+    /// must match its length and weight each hop's share of the broadcaster
+    /// fee (empty means split evenly). `fee_included` picks who bears the
+    /// fee: `true` keeps the legacy behavior (recipient absorbs the fee out
+    /// of `amount`), `false` makes the recipient whole and charges the
+    /// sender on top. Locks in the live quote (see `quote_fee`) and escrows
+    /// the gross amount from `sender`, to be paid out by `claim_payment`.
+    /// Returns payment_id.
     pub fn create_payment(
         env: Env,
         sender: Address,
         recipient: Address,
-        broadcaster: Address,
+        relay_hops: Vec<Address>,
+        hop_weights: Vec<u32>,
         relayer: Address,
         amount: i128,
+        fee_included: bool,
+        token_address: Address,
     ) -> u64 {
         // Verify sender authorization
         sender.require_auth();
 
+        if !hop_weights.is_empty() {
+            if hop_weights.len() != relay_hops.len() {
+                panic!("hop_weights must be empty or match relay_hops length");
+            }
+            // `claim_payment` divides the per-hop split by this sum, and the
+            // payment's funds are escrowed the moment this call returns, so a
+            // zero or overflowing sum here would leave escrowed funds
+            // unrecoverable. Reject it now instead of panicking at claim time.
+            let mut total_weight: u32 = 0;
+            for weight in hop_weights.iter() {
+                total_weight = total_weight
+                    .checked_add(weight)
+                    .expect("hop_weights sum overflow");
+            }
+            if total_weight == 0 {
+                panic!("hop_weights must not sum to zero");
+            }
+        }
+
+        // Reject amounts that could overflow i128 when multiplied by a bps
+        // value down the line.
+        if amount < 0 || amount > MAX_SAFE_AMOUNT {
+            panic!("amount out of safe range");
+        }
+
+        let schedule: FeeSchedule = env
+            .storage()
+            .instance()
+            .get(&DataKey::FeeSchedule)
+            .expect("Fee schedule not set");
+        let metrics = record_payment_metrics(&env);
+        let (total_fee, broadcaster_fee, relayer_fee, protocol_fee, quoted_bps) =
+            quote_fee_breakdown(amount, &schedule, &metrics);
+
+        // `claim_payment` multiplies `broadcaster_fee * weight` for each hop;
+        // a weight large enough relative to broadcaster_fee can overflow
+        // i128 even though the sum fits in u32. Reject that now, before the
+        // funds are escrowed, rather than locking them behind a panic later.
+        for weight in hop_weights.iter() {
+            broadcaster_fee
+                .checked_mul(weight as i128)
+                .expect("hop weight too large for broadcaster fee");
+        }
+
+        // Fee-included: recipient absorbs the fee out of `amount`. Otherwise
+        // the recipient is made whole and the sender is charged the fee on
+        // top, so the gross pulled into escrow differs accordingly.
+        let (net_amount, gross_amount) = if fee_included {
+            (amount - total_fee, amount)
+        } else {
+            (amount, amount + total_fee)
+        };
+
         // Get next payment ID
         let payment_id: u64 = env
             .storage()
@@ -62,17 +386,20 @@ impl MeshPayRewards {
             .get(&DataKey::PaymentCount)
             .unwrap_or(0);
 
-        // Calculate fees
-        let total_fee = (amount * TOTAL_FEE_BPS as i128) / 10000;
-        let net_amount = amount - total_fee;
-
-        // Create payment record
         let payment = Payment {
             sender: sender.clone(),
             recipient: recipient.clone(),
-            broadcaster: broadcaster.clone(),
+            relay_hops,
+            hop_weights,
             relayer: relayer.clone(),
             amount: net_amount,
+            fee_included,
+            broadcaster_fee,
+            relayer_fee,
+            protocol_fee,
+            gross_amount,
+            quoted_bps,
+            token_address: token_address.clone(),
             claimed: false,
         };
 
@@ -86,32 +413,45 @@ impl MeshPayRewards {
             .instance()
             .set(&DataKey::PaymentCount, &(payment_id + 1));
 
+        // Escrow the gross amount into the contract's own address so funds
+        // are guaranteed present before relayers do any work.
+        let token = token::Client::new(&env, &token_address);
+        token.transfer(&sender, &env.current_contract_address(), &gross_amount);
+
         payment_id
     }
 
-    /// Distribute rewards to broadcaster, relayer, and protocol
-    /// token_address: Address of the Stellar Asset Contract (use native XLM on testnet)
-    /// from: Address that will pay the fees (typically the sender)
-    pub fn distribute_rewards(
-        env: Env,
-        payment_id: u64,
-        gross_amount: i128,
-        token_address: Address,
-        from: Address,
-    ) {
-        // Verify authorization from the payer
-        from.require_auth();
-
-        let payment: Payment = env
+    /// Let the recipient withdraw their escrowed payment: pays the
+    /// broadcaster fee across `relay_hops` (or to `relayer` if empty), then
+    /// the relayer and protocol fees, then the recipient's net `amount` — all
+    /// funded from escrow. Panics if already claimed. Returns the net amount
+    /// paid to the recipient.
+    pub fn claim_payment(env: Env, payment_id: u64) -> i128 {
+        let mut payment: Payment = env
             .storage()
             .instance()
             .get(&DataKey::Payment(payment_id))
             .expect("Payment not found");
 
-        // Calculate individual fees
-        let broadcaster_fee = (gross_amount * BROADCASTER_FEE_BPS as i128) / 10000;
-        let relayer_fee = (gross_amount * RELAYER_FEE_BPS as i128) / 10000;
-        let protocol_fee = (gross_amount * PROTOCOL_FEE_BPS as i128) / 10000;
+        if payment.claimed {
+            panic!("Payment already claimed");
+        }
+        payment.recipient.require_auth();
+
+        // Checks-effects-interactions: flip and persist `claimed` before any
+        // token transfer runs, so a reentrant call from a malicious
+        // recipient/relayer/hop contract hits the guard above instead of
+        // replaying the whole distribution against the same escrow.
+        payment.claimed = true;
+        env.storage()
+            .instance()
+            .set(&DataKey::Payment(payment_id), &payment);
+
+        let contract_address = env.current_contract_address();
+        let token = token::Client::new(&env, &payment.token_address);
+        let broadcaster_fee = payment.broadcaster_fee;
+        let relayer_fee = payment.relayer_fee;
+        let protocol_fee = payment.protocol_fee;
 
         // Get protocol address
         let protocol: Address = env
@@ -120,29 +460,94 @@ impl MeshPayRewards {
             .get(&DataKey::Protocol)
             .expect("Protocol address not set");
 
-        // Initialize token client for transfers
-        let token = token::Client::new(&env, &token_address);
+        // Split the broadcaster fee across the relay path. An empty path
+        // falls back to paying the whole broadcaster fee to the submitter
+        // (relayer), since there was no intermediate hop to reward.
+        if payment.relay_hops.is_empty() {
+            token.transfer(&contract_address, &payment.relayer, &broadcaster_fee);
+            accrue_earnings(&env, &payment.relayer, broadcaster_fee);
+            env.events().publish(
+                (String::from_str(&env, "reward_hop"),),
+                (payment.relayer.clone(), 0u32, broadcaster_fee),
+            );
+        } else {
+            let hop_count = payment.relay_hops.len();
+            let total_weight: u32 = if payment.hop_weights.is_empty() {
+                hop_count
+            } else {
+                payment.hop_weights.iter().sum()
+            };
 
-        // Transfer fees to respective parties
-        token.transfer(&from, &payment.broadcaster, &broadcaster_fee);
-        token.transfer(&from, &payment.relayer, &relayer_fee);
-        token.transfer(&from, &protocol, &protocol_fee);
+            let mut distributed: i128 = 0;
+            for i in 0..hop_count {
+                let hop = payment.relay_hops.get(i).unwrap();
+                let weight = if payment.hop_weights.is_empty() {
+                    1u32
+                } else {
+                    payment.hop_weights.get(i).unwrap()
+                };
 
-        // Emit events for tracking
-        env.events().publish(
-            (String::from_str(&env, "reward_broadcaster"),),
-            (payment.broadcaster, broadcaster_fee),
-        );
+                // Assign any rounding dust from integer division to the
+                // final hop so the sum exactly equals the broadcaster fee.
+                let hop_share = if i == hop_count - 1 {
+                    broadcaster_fee - distributed
+                } else {
+                    (broadcaster_fee * weight as i128) / total_weight as i128
+                };
+                distributed += hop_share;
+
+                token.transfer(&contract_address, &hop, &hop_share);
+                accrue_earnings(&env, &hop, hop_share);
+                env.events()
+                    .publish((String::from_str(&env, "reward_hop"),), (hop, i, hop_share));
+            }
+        }
+
+        token.transfer(&contract_address, &payment.relayer, &relayer_fee);
+        token.transfer(&contract_address, &protocol, &protocol_fee);
+        accrue_earnings(&env, &payment.relayer, relayer_fee);
+        accrue_earnings(&env, &protocol, protocol_fee);
+        accrue_totals(&env, broadcaster_fee, relayer_fee, protocol_fee);
 
         env.events().publish(
             (String::from_str(&env, "reward_relayer"),),
-            (payment.relayer, relayer_fee),
+            (payment.relayer.clone(), relayer_fee),
         );
 
         env.events().publish(
             (String::from_str(&env, "reward_protocol"),),
             (protocol, protocol_fee),
         );
+
+        // Pay the recipient their net amount out of escrow. `claimed` was
+        // already flipped and persisted above, before any transfer ran.
+        token.transfer(&contract_address, &payment.recipient, &payment.amount);
+        env.events().publish(
+            (String::from_str(&env, "payment_claimed"),),
+            (payment.recipient.clone(), payment.amount),
+        );
+
+        payment.amount
+    }
+
+    /// Get an address's lifetime earnings from relay/protocol rewards.
+    pub fn get_earnings(env: Env, address: Address) -> i128 {
+        env.storage()
+            .instance()
+            .get(&DataKey::Earnings(address))
+            .unwrap_or(0)
+    }
+
+    /// Get the cumulative fees paid out across all payments, by category.
+    pub fn get_total_distributed(env: Env) -> Totals {
+        env.storage()
+            .instance()
+            .get(&DataKey::TotalDistributed)
+            .unwrap_or(Totals {
+                broadcaster: 0,
+                relayer: 0,
+                protocol: 0,
+            })
     }
 
     /// Get payment details
@@ -161,11 +566,26 @@ impl MeshPayRewards {
             .unwrap_or(0)
     }
 
-    /// Calculate fees for a given amount
+    /// Calculate fees for `amount` at the base, uncongested fee schedule
+    /// rate — i.e. the flat broadcaster/relayer/protocol bps with no
+    /// congestion scaling applied. This will understate what `create_payment`
+    /// actually charges whenever recent volume has pushed the live quote
+    /// above the base rate; callers previewing a real charge should use
+    /// `quote_fee` instead, which is what a payment is locked onto at
+    /// creation time.
     pub fn calculate_fees(env: Env, amount: i128) -> (i128, i128, i128, i128) {
-        let broadcaster_fee = (amount * BROADCASTER_FEE_BPS as i128) / 10000;
-        let relayer_fee = (amount * RELAYER_FEE_BPS as i128) / 10000;
-        let protocol_fee = (amount * PROTOCOL_FEE_BPS as i128) / 10000;
+        if amount < 0 || amount > MAX_SAFE_AMOUNT {
+            panic!("amount out of safe range");
+        }
+
+        let schedule: FeeSchedule = env
+            .storage()
+            .instance()
+            .get(&DataKey::FeeSchedule)
+            .expect("Fee schedule not set");
+        let broadcaster_fee = (amount * schedule.broadcaster_bps as i128) / 10000;
+        let relayer_fee = (amount * schedule.relayer_bps as i128) / 10000;
+        let protocol_fee = (amount * schedule.protocol_bps as i128) / 10000;
         let net_amount = amount - broadcaster_fee - relayer_fee - protocol_fee;
 
         (net_amount, broadcaster_fee, relayer_fee, protocol_fee)
@@ -0,0 +1,381 @@
+#![cfg(test)]
+
+use super::*;
+use soroban_sdk::testutils::Address as _;
+
+fn setup(env: &Env) -> (MeshPayRewardsClient<'static>, Address) {
+    env.mock_all_auths();
+    let contract_id = env.register_contract(None, MeshPayRewards);
+    let client = MeshPayRewardsClient::new(env, &contract_id);
+    let protocol = Address::generate(env);
+    client.initialize(&protocol);
+    (client, protocol)
+}
+
+/// Token mock whose `transfer` is a no-op: these tests exercise payment
+/// bookkeeping and claim semantics, not real token balances.
+mod mock_token {
+    use soroban_sdk::{contract, contractimpl, Address, Env};
+
+    #[contract]
+    pub struct MockToken;
+
+    #[contractimpl]
+    impl MockToken {
+        pub fn transfer(_env: Env, _from: Address, _to: Address, _amount: i128) {}
+    }
+}
+
+fn register_mock_token(env: &Env) -> Address {
+    env.register_contract(None, mock_token::MockToken)
+}
+
+#[test]
+fn test_claim_payment_happy_path() {
+    let env = Env::default();
+    let (client, _protocol) = setup(&env);
+    let token = register_mock_token(&env);
+
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let relayer = Address::generate(&env);
+
+    let payment_id = client.create_payment(
+        &sender,
+        &recipient,
+        &Vec::new(&env),
+        &Vec::new(&env),
+        &relayer,
+        &10_000,
+        &true,
+        &token,
+    );
+
+    let net_paid = client.claim_payment(&payment_id);
+    let payment = client.get_payment(&payment_id);
+
+    assert!(payment.claimed);
+    assert_eq!(net_paid, payment.amount);
+}
+
+#[test]
+#[should_panic(expected = "Payment already claimed")]
+fn test_double_claim_panics() {
+    let env = Env::default();
+    let (client, _protocol) = setup(&env);
+    let token = register_mock_token(&env);
+
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let relayer = Address::generate(&env);
+
+    let payment_id = client.create_payment(
+        &sender,
+        &recipient,
+        &Vec::new(&env),
+        &Vec::new(&env),
+        &relayer,
+        &10_000,
+        &true,
+        &token,
+    );
+
+    client.claim_payment(&payment_id);
+    client.claim_payment(&payment_id);
+}
+
+#[test]
+#[should_panic(expected = "hop_weights must not sum to zero")]
+fn test_zero_weight_hops_rejected_at_creation() {
+    let env = Env::default();
+    let (client, _protocol) = setup(&env);
+    let token = register_mock_token(&env);
+
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let relayer = Address::generate(&env);
+    let hop_a = Address::generate(&env);
+    let hop_b = Address::generate(&env);
+
+    let mut relay_hops = Vec::new(&env);
+    relay_hops.push_back(hop_a);
+    relay_hops.push_back(hop_b);
+
+    let mut hop_weights = Vec::new(&env);
+    hop_weights.push_back(0u32);
+    hop_weights.push_back(0u32);
+
+    client.create_payment(
+        &sender,
+        &recipient,
+        &relay_hops,
+        &hop_weights,
+        &relayer,
+        &10_000,
+        &true,
+        &token,
+    );
+}
+
+/// Token mock that re-enters `claim_payment` for the same payment from
+/// inside its own `transfer` call, simulating a malicious recipient/hop/
+/// relayer contract.
+mod reentrant_token {
+    use soroban_sdk::{contract, contractimpl, symbol_short, Address, Env};
+
+    #[contract]
+    pub struct ReentrantToken;
+
+    #[contractimpl]
+    impl ReentrantToken {
+        pub fn set_target(env: Env, target: Address, payment_id: u64) {
+            env.storage().instance().set(&symbol_short!("tgt"), &target);
+            env.storage()
+                .instance()
+                .set(&symbol_short!("pid"), &payment_id);
+        }
+
+        pub fn transfer(env: Env, _from: Address, _to: Address, _amount: i128) {
+            // Not armed until `set_target` runs, so the escrow transfer made
+            // by `create_payment` passes through without re-entering.
+            let target: Option<Address> = env.storage().instance().get(&symbol_short!("tgt"));
+            let Some(target) = target else {
+                return;
+            };
+            let payment_id: u64 = env.storage().instance().get(&symbol_short!("pid")).unwrap();
+            let client = super::super::MeshPayRewardsClient::new(&env, &target);
+            client.claim_payment(&payment_id);
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "Payment already claimed")]
+fn test_reentrant_claim_is_blocked() {
+    let env = Env::default();
+    let (client, _protocol) = setup(&env);
+
+    let token_id = env.register_contract(None, reentrant_token::ReentrantToken);
+    let token_client = reentrant_token::ReentrantTokenClient::new(&env, &token_id);
+
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let relayer = Address::generate(&env);
+
+    let payment_id = client.create_payment(
+        &sender,
+        &recipient,
+        &Vec::new(&env),
+        &Vec::new(&env),
+        &relayer,
+        &10_000,
+        &true,
+        &token_id,
+    );
+
+    // Point the malicious token at this same payment so its first transfer
+    // call (paying the relayer the broadcaster fee) re-enters claim_payment.
+    token_client.set_target(&client.address, &payment_id);
+
+    client.claim_payment(&payment_id);
+}
+
+#[test]
+fn test_fee_included_false_charges_sender_on_top() {
+    let env = Env::default();
+    let (client, _protocol) = setup(&env);
+    let token = register_mock_token(&env);
+
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let relayer = Address::generate(&env);
+
+    let payment_id = client.create_payment(
+        &sender,
+        &recipient,
+        &Vec::new(&env),
+        &Vec::new(&env),
+        &relayer,
+        &10_000,
+        &false,
+        &token,
+    );
+    let payment = client.get_payment(&payment_id);
+
+    // The recipient is made whole for the full amount...
+    assert_eq!(payment.amount, 10_000);
+    // ...and the sender's escrow covers the fee on top of it.
+    assert_eq!(
+        payment.gross_amount,
+        10_000 + payment.broadcaster_fee + payment.relayer_fee + payment.protocol_fee
+    );
+
+    let net_paid = client.claim_payment(&payment_id);
+    assert_eq!(net_paid, 10_000);
+}
+
+#[test]
+#[should_panic]
+fn test_update_fees_requires_protocol_auth() {
+    let env = Env::default();
+    let contract_id = env.register_contract(None, MeshPayRewards);
+    let client = MeshPayRewardsClient::new(&env, &contract_id);
+    let protocol = Address::generate(&env);
+    client.initialize(&protocol);
+
+    // No auths mocked or provided, so `protocol.require_auth()` inside
+    // `update_fees` must reject this call.
+    client.update_fees(&10, &10, &10);
+}
+
+#[test]
+fn test_weighted_multi_hop_split_assigns_dust_to_last_hop() {
+    let env = Env::default();
+    let (client, _protocol) = setup(&env);
+    let token = register_mock_token(&env);
+
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let relayer = Address::generate(&env);
+    let hop_a = Address::generate(&env);
+    let hop_b = Address::generate(&env);
+
+    let mut relay_hops = Vec::new(&env);
+    relay_hops.push_back(hop_a.clone());
+    relay_hops.push_back(hop_b.clone());
+
+    let mut hop_weights = Vec::new(&env);
+    hop_weights.push_back(1u32);
+    hop_weights.push_back(3u32);
+
+    let payment_id = client.create_payment(
+        &sender,
+        &recipient,
+        &relay_hops,
+        &hop_weights,
+        &relayer,
+        &100_000,
+        &true,
+        &token,
+    );
+    let payment = client.get_payment(&payment_id);
+    let broadcaster_fee = payment.broadcaster_fee;
+
+    // Hop A gets the floor of its 1/4 share; hop B (the last hop) gets the
+    // remainder, so the two shares still sum exactly to broadcaster_fee.
+    let expected_hop_a = broadcaster_fee / 4;
+    let expected_hop_b = broadcaster_fee - expected_hop_a;
+
+    client.claim_payment(&payment_id);
+
+    assert_eq!(client.get_earnings(&hop_a), expected_hop_a);
+    assert_eq!(client.get_earnings(&hop_b), expected_hop_b);
+    assert!(expected_hop_b > (broadcaster_fee * 3) / 4);
+}
+
+#[test]
+fn test_earnings_and_totals_accrue_across_payments() {
+    let env = Env::default();
+    let (client, protocol) = setup(&env);
+    let token = register_mock_token(&env);
+
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let relayer = Address::generate(&env);
+
+    let first_id = client.create_payment(
+        &sender,
+        &recipient,
+        &Vec::new(&env),
+        &Vec::new(&env),
+        &relayer,
+        &10_000,
+        &true,
+        &token,
+    );
+    client.claim_payment(&first_id);
+    let first_payment = client.get_payment(&first_id);
+
+    let second_id = client.create_payment(
+        &sender,
+        &recipient,
+        &Vec::new(&env),
+        &Vec::new(&env),
+        &relayer,
+        &10_000,
+        &true,
+        &token,
+    );
+    client.claim_payment(&second_id);
+    let second_payment = client.get_payment(&second_id);
+
+    // Empty relay path, so the relayer collects both the broadcaster fee
+    // (fallback) and its own relayer fee on each payment.
+    let expected_relayer_earnings = first_payment.broadcaster_fee
+        + first_payment.relayer_fee
+        + second_payment.broadcaster_fee
+        + second_payment.relayer_fee;
+    assert_eq!(client.get_earnings(&relayer), expected_relayer_earnings);
+
+    let expected_protocol_earnings = first_payment.protocol_fee + second_payment.protocol_fee;
+    assert_eq!(client.get_earnings(&protocol), expected_protocol_earnings);
+
+    let totals = client.get_total_distributed();
+    assert_eq!(
+        totals.broadcaster,
+        first_payment.broadcaster_fee + second_payment.broadcaster_fee
+    );
+    assert_eq!(
+        totals.relayer,
+        first_payment.relayer_fee + second_payment.relayer_fee
+    );
+    assert_eq!(
+        totals.protocol,
+        first_payment.protocol_fee + second_payment.protocol_fee
+    );
+}
+
+#[test]
+#[should_panic(expected = "Earnings overflow for address")]
+fn test_earnings_overflow_panics() {
+    let env = Env::default();
+    let contract_id = env.register_contract(None, MeshPayRewards);
+    let address = Address::generate(&env);
+
+    env.as_contract(&contract_id, || {
+        accrue_earnings(&env, &address, i128::MAX);
+        accrue_earnings(&env, &address, 1);
+    });
+}
+
+#[test]
+fn test_quote_fee_diverges_from_calculate_fees_under_congestion() {
+    let env = Env::default();
+    let (client, _protocol) = setup(&env);
+    let token = register_mock_token(&env);
+
+    let sender = Address::generate(&env);
+    let recipient = Address::generate(&env);
+    let relayer = Address::generate(&env);
+
+    // Flood enough payments into the current window to push the live quote
+    // above the base rate (METRICS_CAPACITY is 50 payments per window).
+    for _ in 0..60 {
+        let id = client.create_payment(
+            &sender,
+            &recipient,
+            &Vec::new(&env),
+            &Vec::new(&env),
+            &relayer,
+            &10_000,
+            &true,
+            &token,
+        );
+        client.claim_payment(&id);
+    }
+
+    let (quoted_net, _, _, _) = client.quote_fee(&100_000);
+    let (flat_net, _, _, _) = client.calculate_fees(&100_000);
+
+    assert!(quoted_net < flat_net);
+}